@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::sync::{Condvar, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
-use serde::Deserialize;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
 
 use anyhow::{Result};
 use badges::{BadgeBuilder, BadgeColor, BadgeStyle};
@@ -21,20 +22,63 @@ FLAGS:
     -h, --help      Prints help information.
 
 OPTIONS:
-    --delay NUMBER  Set delay between each outbound query (default: 1000).
-    --dest PATH     Specify path where to put resulting badge SVGs.
-    --badges PATH   Specify path to badge information TOML.
+    --delay NUMBER        Set delay between each outbound query per host (default: 1000).
+    --concurrency NUMBER  Cap simultaneous in-flight requests (default: 4).
+    --driver URL          Drive a WebDriver session at URL instead of the plain scraper.
+    --driver-binary PATH  Path to the browser binary the WebDriver should launch.
+    --state PATH          Persist last-rendered counts and only rewrite changed badges.
+    --force               Rewrite every badge even when its count is unchanged.
+    --format FORMAT       Output format: 'svg' (default) or 'endpoint' (shields.io JSON).
+    --dest PATH           Specify path where to put resulting badge files (SVG or JSON).
+    --badges PATH         Specify path to badge information TOML.
+
+BADGE TOML:
+    Each badge table may also set appearance fields, all optional:
+    style, label, label_color, message_color, and count_format.
+    Set opera_downloads = true to fold Opera's downloads figure into the
+    total (off by default, as it is not a user count).
+    count_format controls how the number is rendered: 'comma' (12,345),
+    'abbrev' (12k/1.2M), or omit it for the raw count.
 ";
 
 #[derive(Deserialize, Debug)]
 struct ExtensionPages {
     chrome: Option<String>,
     mozilla: Option<String>,
+    edge: Option<String>,
+    opera: Option<String>,
+    /// Opera publishes only a *downloads* figure, not a user count. It is
+    /// left out of the `users` total unless this is set, since summing
+    /// downloads into a user count conflates two different metrics.
+    opera_downloads: Option<bool>,
+    /// `owner/repo` whose GitHub stargazer count is added to the total.
+    github: Option<String>,
+    /// Short-circuit scraping with a hand-maintained count. Use when a store
+    /// layout changed or the metric can't be counted automatically; pair it
+    /// with `override_reason` so the next maintainer knows why.
+    override_count: Option<u32>,
+    override_reason: Option<String>,
+    /// Badge appearance. Every field is optional and defaults to the
+    /// hardcoded look the tool shipped with, so existing `badges.toml`
+    /// files render byte-for-byte identically.
+    style: Option<String>,
+    label: Option<String>,
+    label_color: Option<String>,
+    message_color: Option<String>,
+    /// How the count is rendered: `comma` (12,345), `abbrev` (12k/1.2M), or
+    /// unset for the raw number. Distinct from the `--format` output mode.
+    count_format: Option<String>,
 }
 
 #[derive(Debug)]
 struct AppArgs {
     delay: u64,
+    concurrency: usize,
+    driver_url: Option<String>,
+    driver_binary: Option<String>,
+    state_path: Option<String>,
+    force: bool,
+    format: String,
     dest_path: String,
     badges_toml_path: String
 }
@@ -50,6 +94,12 @@ fn parse_args() -> Result<AppArgs, pico_args::Error> {
 
     let args = AppArgs {
         delay: pargs.opt_value_from_str("--delay")?.unwrap_or(1000),
+        concurrency: pargs.opt_value_from_str("--concurrency")?.unwrap_or(4),
+        driver_url: pargs.opt_value_from_str("--driver")?,
+        driver_binary: pargs.opt_value_from_str("--driver-binary")?,
+        state_path: pargs.opt_value_from_str("--state")?,
+        force: pargs.contains("--force"),
+        format: pargs.opt_value_from_str("--format")?.unwrap_or("svg".to_string()),
         dest_path: pargs.opt_value_from_str("--dest")?.unwrap_or(".".to_string()),
         badges_toml_path: pargs.opt_value_from_str("--badges")?.unwrap_or("./badges.toml".to_string()),
     };
@@ -93,7 +143,7 @@ fn extract_chrome_webstore_users(page_contents: &str) -> Option<u32> {
     let noscript_end = page_contents.rfind("</noscript>");
 
     if noscript_start.is_none() || noscript_end.is_none() {
-        std::process::exit(-1);
+        return None;
     }
 
     let start_idx = noscript_start.unwrap();
@@ -124,45 +174,616 @@ fn extract_chrome_webstore_users(page_contents: &str) -> Option<u32> {
     None
 }
 
-fn fetch_page(url: String) -> Result<String> {
-    let contents = ureq::get(&url)
-        .call()?
-        .into_string()?;
-    Ok(contents)
+fn extract_edge_addon_users(page_contents: &str) -> Option<u32> {
+    let document = Document::from(page_contents);
+
+    // The Edge Add-ons page labels the figure with an explicit tile; the
+    // count lives in the sibling value element next to the "Users" heading.
+    let headings = document.find(
+        And(Name("div"), Class("nsdWHlHH")).descendant(Name("span")),
+    );
+
+    for node in headings {
+        if node.text().trim() == "Users" {
+            let value = node
+                .parent()
+                .unwrap()
+                .children()
+                .find(|c| c.name().unwrap_or("") == "span" && c.text().trim() != "Users");
+
+            if let Some(value) = value {
+                return value.text().replace([',', ' '], "").parse::<u32>().ok();
+            }
+        }
+    }
+
+    None
+}
+
+/// Scrapes Opera's download figure (the store exposes no user count).
+fn extract_opera_addon_users(page_contents: &str) -> Option<u32> {
+    let document = Document::from(page_contents);
+
+    // Opera renders the download count in a definition list much like
+    // Mozilla's, so we look for the term labelled "Downloads".
+    for node in document.find(And(Name("dt"), Class("name"))) {
+        if node.text().trim() == "Downloads" {
+            let value = node
+                .parent()
+                .unwrap()
+                .children()
+                .find(|c| c.name().unwrap_or("") == "dd");
+
+            if let Some(value) = value {
+                return value.text().replace([',', ' '], "").parse::<u32>().ok();
+            }
+        }
+    }
+
+    None
+}
+
+fn extract_github_stars(body: &str) -> Option<u32> {
+    let payload: serde_json::Value = serde_json::from_str(body).ok()?;
+    payload["stargazers_count"].as_u64().map(|count| count as u32)
+}
+
+/// A single place a count can come from: a store listing or an API. Each
+/// source knows the host it talks to (for per-host throttling), how to turn
+/// a badge's identifier into a URL, and how to pull the number back out.
+trait MetricSource: Sync {
+    fn host(&self) -> &'static str;
+    fn url(&self, id: &str) -> String;
+    fn ready_marker(&self) -> Option<&'static str> {
+        None
+    }
+    /// Whether this source scrapes a JS-rendered HTML page and so benefits
+    /// from the WebDriver fetcher when `--driver` is set. API/JSON sources
+    /// return `false`: driving their endpoints through a browser wraps the
+    /// payload in page chrome and breaks parsing, so they always use the
+    /// plain `ureq` fetcher.
+    fn browser_rendered(&self) -> bool {
+        true
+    }
+    fn extract(&self, page: &str) -> Option<u32>;
+}
+
+struct ChromeSource;
+struct MozillaSource;
+struct EdgeSource;
+struct OperaSource;
+struct GithubSource;
+
+impl MetricSource for ChromeSource {
+    fn host(&self) -> &'static str {
+        "chrome.google.com"
+    }
+    fn url(&self, id: &str) -> String {
+        format!("https://chrome.google.com/webstore/detail/{id}")
+    }
+    fn ready_marker(&self) -> Option<&'static str> {
+        Some("<noscript>")
+    }
+    fn extract(&self, page: &str) -> Option<u32> {
+        extract_chrome_webstore_users(page)
+    }
+}
+
+impl MetricSource for MozillaSource {
+    fn host(&self) -> &'static str {
+        "addons.mozilla.org"
+    }
+    fn url(&self, id: &str) -> String {
+        format!("https://addons.mozilla.org/en-US/firefox/addon/{id}/")
+    }
+    fn ready_marker(&self) -> Option<&'static str> {
+        Some("MetadataCard-title")
+    }
+    fn extract(&self, page: &str) -> Option<u32> {
+        extract_mozilla_addon_users(page)
+    }
+}
+
+impl MetricSource for EdgeSource {
+    fn host(&self) -> &'static str {
+        "microsoftedge.microsoft.com"
+    }
+    fn url(&self, id: &str) -> String {
+        format!("https://microsoftedge.microsoft.com/addons/detail/{id}")
+    }
+    fn ready_marker(&self) -> Option<&'static str> {
+        // The count tile is injected client-side; wait for its container.
+        Some("nsdWHlHH")
+    }
+    fn extract(&self, page: &str) -> Option<u32> {
+        extract_edge_addon_users(page)
+    }
+}
+
+impl MetricSource for OperaSource {
+    fn host(&self) -> &'static str {
+        "addons.opera.com"
+    }
+    fn url(&self, id: &str) -> String {
+        format!("https://addons.opera.com/en/extensions/details/{id}/")
+    }
+    fn ready_marker(&self) -> Option<&'static str> {
+        // The stats definition list renders after hydration; wait for it.
+        Some("Downloads")
+    }
+    fn extract(&self, page: &str) -> Option<u32> {
+        extract_opera_addon_users(page)
+    }
+}
+
+impl MetricSource for GithubSource {
+    fn host(&self) -> &'static str {
+        "api.github.com"
+    }
+    fn url(&self, id: &str) -> String {
+        format!("https://api.github.com/repos/{id}")
+    }
+    fn browser_rendered(&self) -> bool {
+        false
+    }
+    fn extract(&self, page: &str) -> Option<u32> {
+        extract_github_stars(page)
+    }
+}
+
+static CHROME_SOURCE: ChromeSource = ChromeSource;
+static MOZILLA_SOURCE: MozillaSource = MozillaSource;
+static EDGE_SOURCE: EdgeSource = EdgeSource;
+static OPERA_SOURCE: OperaSource = OperaSource;
+static GITHUB_SOURCE: GithubSource = GithubSource;
+
+/// The sources configured for a single badge, paired with the identifier
+/// each one should look up.
+fn sources_for(pages: &ExtensionPages) -> Vec<(&'static dyn MetricSource, String)> {
+    let mut sources: Vec<(&'static dyn MetricSource, String)> = Vec::new();
+
+    if let Some(id) = pages.chrome.clone() {
+        sources.push((&CHROME_SOURCE, id));
+    }
+    if let Some(id) = pages.mozilla.clone() {
+        sources.push((&MOZILLA_SOURCE, id));
+    }
+    if let Some(id) = pages.edge.clone() {
+        sources.push((&EDGE_SOURCE, id));
+    }
+    if let (Some(id), Some(true)) = (pages.opera.clone(), pages.opera_downloads) {
+        sources.push((&OPERA_SOURCE, id));
+    }
+    if let Some(id) = pages.github.clone() {
+        sources.push((&GITHUB_SOURCE, id));
+    }
+
+    sources
+}
+
+/// How to turn a store URL into the HTML we scrape a count out of.
+///
+/// The plain [`UreqFetcher`] grabs the server-rendered markup, which is
+/// enough for the `<noscript>` fallbacks the extractors rely on today. The
+/// [`WebDriverFetcher`] drives a real browser so counts that only exist
+/// after client-side rendering can still be scraped.
+trait Fetcher: Sync {
+    /// Fetch `url` and return its page source. `ready` names a marker that
+    /// a rendering fetcher polls for before returning, giving JS-rendered
+    /// counts time to appear; [`UreqFetcher`] ignores it.
+    fn fetch(&self, url: &str, ready: Option<&str>) -> Result<String>;
+}
+
+/// The original behaviour: a single blocking `ureq` GET.
+struct UreqFetcher;
+
+impl Fetcher for UreqFetcher {
+    fn fetch(&self, url: &str, _ready: Option<&str>) -> Result<String> {
+        let contents = ureq::get(url)
+            .call()?
+            .into_string()?;
+        Ok(contents)
+    }
 }
 
-fn generate_users_badge(pages: &ExtensionPages, delay: u64) -> Result<String, anyhow::Error>{
-    let mut total_count = 0;
+/// Drives a WebDriver session (geckodriver/chromedriver) over the W3C
+/// WebDriver HTTP protocol: open a session, navigate, poll the page source
+/// until the count shows up, then tear the session back down.
+struct WebDriverFetcher {
+    url: String,
+    binary: Option<String>,
+}
+
+impl WebDriverFetcher {
+    fn new(url: String, binary: Option<String>) -> WebDriverFetcher {
+        WebDriverFetcher { url, binary }
+    }
+
+    /// Build the `alwaysMatch` capabilities for both geckodriver and
+    /// chromedriver: a headless argument plus the optional browser binary
+    /// path, under each vendor's options key. The driver ignores the key
+    /// that doesn't apply to it.
+    fn capabilities(&self) -> serde_json::Value {
+        let mut firefox_options = serde_json::json!({ "args": ["-headless"] });
+        let mut chrome_options = serde_json::json!({ "args": ["--headless=new"] });
+        if let Some(binary) = &self.binary {
+            firefox_options["binary"] = serde_json::Value::String(binary.clone());
+            chrome_options["binary"] = serde_json::Value::String(binary.clone());
+        }
+
+        serde_json::json!({
+            "capabilities": {
+                "alwaysMatch": {
+                    "moz:firefoxOptions": firefox_options,
+                    "goog:chromeOptions": chrome_options
+                }
+            }
+        })
+    }
 
-    if let Some(chrome_id) = pages.chrome.clone() {
-        sleep(Duration::from_millis(delay));
+    fn new_session(&self) -> Result<String> {
+        let response: serde_json::Value = ureq::post(&format!("{}/session", self.url))
+            .send_json(self.capabilities())?
+            .into_json()?;
+        let session_id = response["value"]["sessionId"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("WebDriver did not return a session id"))?;
+        Ok(session_id.to_string())
+    }
 
-        let url = format!("https://chrome.google.com/webstore/detail/{chrome_id}");
-        let store_page = fetch_page(url)?;
-        let user_count = extract_chrome_webstore_users(&store_page).unwrap_or(0);
-        total_count += user_count;
+    fn navigate(&self, session_id: &str, url: &str) -> Result<()> {
+        ureq::post(&format!("{}/session/{}/url", self.url, session_id))
+            .send_json(serde_json::json!({ "url": url }))?;
+        Ok(())
     }
 
-    if let Some(mozilla_id) = pages.mozilla.clone() {
-        sleep(Duration::from_millis(delay));
+    fn page_source(&self, session_id: &str) -> Result<String> {
+        let response: serde_json::Value =
+            ureq::get(&format!("{}/session/{}/source", self.url, session_id))
+                .call()?
+                .into_json()?;
+        Ok(response["value"].as_str().unwrap_or("").to_string())
+    }
 
-        let url = format!("https://addons.mozilla.org/en-US/firefox/addon/{mozilla_id}/");
-        let store_page = fetch_page(url)?;
-        let user_count = extract_mozilla_addon_users(&store_page).unwrap_or(0);
-        total_count += user_count;
+    fn delete_session(&self, session_id: &str) {
+        // Best effort: a leaked session is noise, not a reason to fail the run.
+        let _ = ureq::delete(&format!("{}/session/{}", self.url, session_id)).call();
     }
+}
 
-    let badge_svg = BadgeBuilder::new()
-        .style(BadgeStyle::Flat)
-        .label("users")
-        .message(&format!("{}", total_count))
-        .message_color(BadgeColor::CustomRgb(0x0, 0x7e, 0xc6))
-        .render()
-        .expect("failed to render badge");
+impl Fetcher for WebDriverFetcher {
+    fn fetch(&self, url: &str, ready: Option<&str>) -> Result<String> {
+        let session_id = self.new_session()?;
+        let result = (|| {
+            self.navigate(&session_id, url)?;
+
+            // Poll the rendered source until the count element shows up, or
+            // give up and return whatever we last saw.
+            let mut source = self.page_source(&session_id)?;
+            for _ in 0..20 {
+                match ready {
+                    Some(marker) if !source.contains(marker) => {}
+                    _ => break,
+                }
+                sleep(Duration::from_millis(500));
+                source = self.page_source(&session_id)?;
+            }
+            Ok(source)
+        })();
+
+        self.delete_session(&session_id);
+        result
+    }
+}
+
+/// Counting semaphore used to bound how many store requests run at once.
+///
+/// The standard library has no semaphore, so we build the usual
+/// `Mutex`/`Condvar` pair: `acquire` blocks while no permits remain and
+/// `release` hands one back, waking a single waiter.
+struct Semaphore {
+    permits: Mutex<usize>,
+    available: Condvar,
+}
+
+impl Semaphore {
+    fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            permits: Mutex::new(permits),
+            available: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.available.wait(permits).unwrap();
+        }
+        *permits -= 1;
+    }
+
+    fn release(&self) {
+        let mut permits = self.permits.lock().unwrap();
+        *permits += 1;
+        self.available.notify_one();
+    }
+}
+
+/// Keeps store requests aimed at the same host spaced at least `delay` apart.
+///
+/// Each host tracks the earliest instant at which its next request may
+/// start; `wait` reserves the following slot and sleeps off the remainder
+/// outside the lock so other hosts are never blocked by our nap.
+struct HostThrottle {
+    delay: u64,
+    next_allowed: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl HostThrottle {
+    fn new(delay: u64) -> HostThrottle {
+        HostThrottle {
+            delay,
+            next_allowed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn wait(&self, host: &'static str) {
+        let pause = {
+            let mut slots = self.next_allowed.lock().unwrap();
+            let now = Instant::now();
+            let start = slots.get(host).copied().unwrap_or(now).max(now);
+            slots.insert(host, start + Duration::from_millis(self.delay));
+            start.saturating_duration_since(now)
+        };
+
+        if !pause.is_zero() {
+            sleep(pause);
+        }
+    }
+}
+
+/// A single store page to scrape, tagged with the badge it contributes to.
+struct StoreRequest {
+    badge_name: String,
+    url: String,
+    source: &'static dyn MetricSource,
+}
+
+/// Expand the configured badges into the flat list of store requests they
+/// imply. Each badge may reference several sources; their counts are summed
+/// back together once everything has been fetched. Badges pinned with an
+/// `override_count` contribute no requests — their total is substituted in
+/// during aggregation.
+fn plan_requests(badges_info: &HashMap<String, ExtensionPages>) -> Vec<StoreRequest> {
+    let mut requests = Vec::new();
+
+    for (badge_name, pages) in badges_info {
+        if pages.override_count.is_some() {
+            continue;
+        }
+
+        for (source, id) in sources_for(pages) {
+            requests.push(StoreRequest {
+                badge_name: badge_name.clone(),
+                url: source.url(&id),
+                source,
+            });
+        }
+    }
+
+    requests
+}
+
+/// Fetch every store request concurrently, capping in-flight requests with
+/// a permit pool and throttling each host independently, then fold the
+/// results back into a badge-name -> total-count map.
+fn collect_counts(
+    requests: Vec<StoreRequest>,
+    fetcher: &dyn Fetcher,
+    api_fetcher: &dyn Fetcher,
+    concurrency: usize,
+    delay: u64,
+) -> HashMap<String, u32> {
+    let semaphore = Semaphore::new(concurrency.max(1));
+    let throttle = HostThrottle::new(delay);
+    let totals: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+
+    std::thread::scope(|scope| {
+        for request in &requests {
+            scope.spawn(|| {
+                // Sleep off the per-host delay before taking a permit, so a
+                // burst of same-host requests can't hold the whole pool idle
+                // in `wait` and starve other hosts.
+                throttle.wait(request.source.host());
+
+                // API/JSON sources always use the plain fetcher; only
+                // browser-rendered store pages route through `--driver`.
+                let selected = if request.source.browser_rendered() {
+                    fetcher
+                } else {
+                    api_fetcher
+                };
+
+                semaphore.acquire();
+                let count = match selected.fetch(&request.url, request.source.ready_marker()) {
+                    Ok(page) => request.source.extract(&page).unwrap_or(0),
+                    Err(_) => 0,
+                };
+                semaphore.release();
+
+                let mut totals = totals.lock().unwrap();
+                *totals.entry(request.badge_name.clone()).or_insert(0) += count;
+            });
+        }
+    });
+
+    totals.into_inner().unwrap()
+}
+
+fn parse_style(name: &str) -> BadgeStyle {
+    match name.to_ascii_lowercase().as_str() {
+        "plastic" => BadgeStyle::Plastic,
+        "for-the-badge" | "for_the_badge" => BadgeStyle::ForTheBadge,
+        _ => BadgeStyle::Flat,
+    }
+}
+
+/// Parse a `#rrggbb`/`rrggbb` hex string into a badge colour. Anything that
+/// isn't six hex digits is ignored so a typo falls back to the default.
+fn parse_color(spec: &str) -> Option<BadgeColor> {
+    let hex = spec.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+    Some(BadgeColor::CustomRgb(r, g, b))
+}
+
+/// Insert thousands separators, e.g. `12345` -> `12,345`.
+fn group_thousands(count: u32) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::new();
+    for (idx, ch) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+/// Abbreviate large counts the way common badge generators do: `12k`,
+/// `1.2M`, keeping one decimal only when the leading value is small. The
+/// decimal is truncated, not rounded, so a count just under a power of ten
+/// (e.g. `9_999`) never renders larger than the round value above it.
+fn abbreviate(count: u32) -> String {
+    let (value, suffix) = if count >= 1_000_000 {
+        (count as f64 / 1_000_000.0, "M")
+    } else if count >= 1_000 {
+        (count as f64 / 1_000.0, "k")
+    } else {
+        return count.to_string();
+    };
+
+    if value < 10.0 {
+        format!("{}{}", (value * 10.0).trunc() / 10.0, suffix)
+    } else {
+        format!("{}{}", value.trunc() as u32, suffix)
+    }
+}
+
+fn format_count(count: u32, format: Option<&str>) -> String {
+    match format {
+        Some("comma") => group_thousands(count),
+        Some("abbrev") => abbreviate(count),
+        _ => count.to_string(),
+    }
+}
+
+/// A shields.io ["endpoint"](https://shields.io/badges/endpoint-badge)
+/// document. Hosting this JSON lets shields.io render a live badge instead
+/// of committing a static SVG.
+#[derive(Serialize, Debug)]
+struct EndpointBadge {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    label: String,
+    message: String,
+    color: String,
+}
+
+/// Render the same label/message/colour triple used for the SVG, serialized
+/// as a shields.io endpoint document rather than drawn.
+fn render_endpoint_badge(pages: &ExtensionPages, total_count: u32) -> Result<String, anyhow::Error> {
+    let label = pages.label.as_deref().unwrap_or("users").to_string();
+    let message = format_count(total_count, pages.count_format.as_deref());
+    // Validate the same way the SVG renderer does, so a typo'd colour falls
+    // back to the default instead of emitting an invalid endpoint document.
+    let color = pages
+        .message_color
+        .as_deref()
+        .filter(|spec| parse_color(spec).is_some())
+        .map(|spec| spec.trim_start_matches('#').to_string())
+        .unwrap_or_else(|| "007ec6".to_string());
+
+    let badge = EndpointBadge {
+        schema_version: 1,
+        label,
+        message,
+        color,
+    };
+
+    Ok(serde_json::to_string(&badge)?)
+}
+
+fn render_users_badge(pages: &ExtensionPages, total_count: u32) -> Result<String, anyhow::Error> {
+    let style = pages.style.as_deref().map(parse_style).unwrap_or(BadgeStyle::Flat);
+    let label = pages.label.as_deref().unwrap_or("users");
+    let message = format_count(total_count, pages.count_format.as_deref());
+    let message_color = pages
+        .message_color
+        .as_deref()
+        .and_then(parse_color)
+        .unwrap_or(BadgeColor::CustomRgb(0x0, 0x7e, 0xc6));
+
+    let mut builder = BadgeBuilder::new();
+    builder
+        .style(style)
+        .label(label)
+        .message(&message)
+        .message_color(message_color);
+
+    if let Some(label_color) = pages.label_color.as_deref().and_then(parse_color) {
+        builder.label_color(label_color);
+    }
+
+    let badge_svg = builder.render().expect("failed to render badge");
 
     Ok(badge_svg)
 }
 
+/// Last rendered count for a single badge, plus when we saw it.
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct BadgeRecord {
+    count: u32,
+    updated_at: u64,
+}
+
+/// Persisted counts keyed by badge name, mirroring the `badges.toml` layout
+/// so the state file stays a readable TOML table per badge.
+type BadgeState = HashMap<String, BadgeRecord>;
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Read the state file, treating a missing file as an empty slate.
+fn load_state(path: &str) -> BadgeState {
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents)
+                .expect("Failed to read state file!");
+            toml::from_str(&contents).expect("Failed to parse state file")
+        }
+        Err(_) => BadgeState::new(),
+    }
+}
+
+fn save_state(path: &str, state: &BadgeState) {
+    let contents = toml::to_string(state).expect("Failed to serialize state");
+    File::create(path)
+        .expect("Failed to create state file")
+        .write_all(contents.as_bytes())
+        .expect("Failed to write state file");
+}
+
 fn main() {
     let config = parse_args().unwrap();
 
@@ -175,20 +796,170 @@ fn main() {
     let badges_info: HashMap<String, ExtensionPages> = toml::from_str(&badges_toml)
         .expect("Failed to parse badges TOML");
 
+    let fetcher: Box<dyn Fetcher> = match config.driver_url.clone() {
+        Some(url) => Box::new(WebDriverFetcher::new(url, config.driver_binary.clone())),
+        None => Box::new(UreqFetcher),
+    };
+    // API/JSON sources always fetch over plain HTTP, even when a driver is set.
+    let api_fetcher = UreqFetcher;
+
+    let requests = plan_requests(&badges_info);
+    let counts = collect_counts(
+        requests,
+        fetcher.as_ref(),
+        &api_fetcher,
+        config.concurrency,
+        config.delay,
+    );
+
+    let mut state = config
+        .state_path
+        .as_deref()
+        .map(load_state)
+        .unwrap_or_default();
+
     for badge_name in badges_info.keys() {
-        println!("Generating badge for '{badge_name}'...");
         let pages = badges_info.get(badge_name).unwrap();
-        let badge_svg = generate_users_badge(&pages, config.delay);
+        let total_count = match pages.override_count {
+            Some(count) => {
+                if let Some(reason) = pages.override_reason.as_deref() {
+                    println!("Using override for '{badge_name}' ({reason}).");
+                }
+                count
+            }
+            None => counts.get(badge_name).copied().unwrap_or(0),
+        };
+
+        // When state tracking is on, skip badges whose count is unchanged so
+        // we don't churn the SVGs that get committed next to them.
+        let previous = state.get(badge_name).map(|record| record.count);
+        let unchanged = previous == Some(total_count);
+        if config.state_path.is_some() && unchanged && !config.force {
+            continue;
+        }
 
-        if badge_svg.is_err() {
+        println!("Generating badge for '{badge_name}'...");
+        match previous {
+            Some(old) if old != total_count => println!("  {badge_name}: {old} -> {total_count}"),
+            None => println!("  {badge_name}: + {total_count}"),
+            _ => {}
+        }
+
+        let (extension, rendered) = match config.format.as_str() {
+            "endpoint" => ("json", render_endpoint_badge(pages, total_count)),
+            _ => ("svg", render_users_badge(pages, total_count)),
+        };
+
+        if rendered.is_err() {
             println!("Failed to generate badge for '{badge_name}'!");
             continue;
         }
 
         // TODO: Use proper path buffers
-        let mut file = File::create(format!("{}/{}.svg", config.dest_path, badge_name))
-            .expect("Failed to create SVG file");
-        file.write_all(badge_svg.unwrap().as_ref()).expect("Failed to write SVG contents into file");
+        let mut file = File::create(format!("{}/{}.{}", config.dest_path, badge_name, extension))
+            .expect("Failed to create badge file");
+        file.write_all(rendered.unwrap().as_ref()).expect("Failed to write badge contents into file");
+
+        state.insert(
+            badge_name.clone(),
+            BadgeRecord { count: total_count, updated_at: now_unix() },
+        );
+    }
+
+    if let Some(state_path) = config.state_path.as_deref() {
+        save_state(state_path, &state);
     }
 
 }
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+
+    #[test]
+    fn group_thousands_inserts_separators() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(12345), "12,345");
+        assert_eq!(group_thousands(1000000), "1,000,000");
+    }
+
+    #[test]
+    fn abbreviate_scales_large_counts() {
+        assert_eq!(abbreviate(999), "999");
+        assert_eq!(abbreviate(1200), "1.2k");
+        assert_eq!(abbreviate(9_999), "9.9k");
+        assert_eq!(abbreviate(12000), "12k");
+        assert_eq!(abbreviate(999_999), "999k");
+        assert_eq!(abbreviate(1_200_000), "1.2M");
+    }
+
+    #[test]
+    fn format_count_selects_style() {
+        assert_eq!(format_count(12345, None), "12345");
+        assert_eq!(format_count(12345, Some("comma")), "12,345");
+        assert_eq!(format_count(12345, Some("abbrev")), "12k");
+        assert_eq!(format_count(12345, Some("bogus")), "12345");
+    }
+
+    #[test]
+    fn parse_color_validates_six_hex_digits() {
+        assert!(matches!(parse_color("#007ec6"), Some(BadgeColor::CustomRgb(0, 0x7e, 0xc6))));
+        assert!(matches!(parse_color("007ec6"), Some(BadgeColor::CustomRgb(0, 0x7e, 0xc6))));
+        assert!(parse_color("#fff").is_none());
+        assert!(parse_color("nothex").is_none());
+    }
+
+    #[test]
+    fn parse_style_falls_back_to_flat() {
+        assert!(matches!(parse_style("plastic"), BadgeStyle::Plastic));
+        assert!(matches!(parse_style("for-the-badge"), BadgeStyle::ForTheBadge));
+        assert!(matches!(parse_style("anything-else"), BadgeStyle::Flat));
+    }
+}
+
+#[cfg(test)]
+mod extract_tests {
+    use super::*;
+
+    #[test]
+    fn mozilla_reads_user_count() {
+        let html = r#"<dl class="MetadataCard-list">
+            <dt class="MetadataCard-title">Users</dt><dd>5000</dd>
+        </dl>"#;
+        assert_eq!(extract_mozilla_addon_users(html), Some(5000));
+    }
+
+    #[test]
+    fn chrome_reads_count_from_noscript() {
+        let html = r#"<html><body>
+            <noscript><span title="1234 users">1234 users</span></noscript>
+        </body></html>"#;
+        assert_eq!(extract_chrome_webstore_users(html), Some(1234));
+    }
+
+    #[test]
+    fn chrome_missing_noscript_returns_none() {
+        assert_eq!(extract_chrome_webstore_users("<html></html>"), None);
+    }
+
+    #[test]
+    fn edge_reads_count_from_tile() {
+        let html = r#"<div class="nsdWHlHH">
+            <span>Users</span><span>12,345</span>
+        </div>"#;
+        assert_eq!(extract_edge_addon_users(html), Some(12345));
+    }
+
+    #[test]
+    fn opera_reads_download_count() {
+        let html = r#"<dl><dt class="name">Downloads</dt><dd>1,234</dd></dl>"#;
+        assert_eq!(extract_opera_addon_users(html), Some(1234));
+    }
+
+    #[test]
+    fn github_reads_stargazers() {
+        assert_eq!(extract_github_stars(r#"{"stargazers_count": 42}"#), Some(42));
+        assert_eq!(extract_github_stars("<html>not json</html>"), None);
+    }
+}